@@ -0,0 +1,229 @@
+//! A `Linker`-wiring registry, as a first step towards composing
+//! [`WasiCtx`](super::WasiCtx) host implementations out of independently
+//! registrable pieces.
+//!
+//! Today an embedder that wants to customize a single WASI interface group
+//! (say, swap in a custom clocks implementation, or drop filesystem access
+//! entirely) has to construct a full [`WasiCtx`](super::WasiCtx) and
+//! implement the whole [`WasiView`](super::WasiView) trait. The long-term
+//! goal is a [`WasiFactor`] per interface group (clocks, filesystem,
+//! cli/environment, sockets, random) that each own their own slice of
+//! per-store state and can be dropped or swapped independently.
+//!
+//! [`ClocksFactor`] is the first concrete factor: it owns a per-instance
+//! [`WasiClocks`] accessed through a plain `fn(&mut T) -> &mut WasiClocks`
+//! projection, the same shape `bindgen!`'s own generated `add_to_linker`
+//! functions expect -- so plugging a factor's interfaces into a `Linker<T>`
+//! never requires `T` to implement the whole [`WasiView`](super::WasiView)
+//! trait, only to expose the one slice of state that factor needs.
+//!
+//! A registry with only a clocks factor in it isn't yet much of a win over
+//! constructing a `Linker` by hand -- the payoff is in dropping the
+//! filesystem or sockets factor entirely for a sandbox that shouldn't have
+//! either, and neither of those factors exists yet. `FactorRegistry` also
+//! doesn't have a seat at the table in `WasiCtxBuilder` or the `with:` map
+//! `bindgen!` consumes: an embedder assembles one and calls
+//! `add_to_linker` themselves rather than handing the registry to a
+//! builder method. `ClocksFactor`'s `fn(&mut T) -> &mut WasiClocks`
+//! projection is the part worth keeping as filesystem and sockets factors
+//! get written; the builder/`with:` integration is a separate step on top
+//! of that.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use wasmtime::component::Linker;
+
+use super::bindings::wasi::clocks::{monotonic_clock, wall_clock};
+use super::{HostMonotonicClock, HostWallClock};
+
+/// One independently registrable slice of the WASI host implementation
+/// (e.g. clocks, filesystem/preopens, cli/environment, sockets, random).
+///
+/// Implementors contribute their own `add_to_linker` calls for the
+/// `wasi:*` interfaces they own. A factor is intentionally narrower than
+/// [`WasiView`](super::WasiView): it only needs access to whatever slice of
+/// `T` it requires, not the whole context.
+pub trait WasiFactor<T: 'static>: Send + Sync {
+    /// A short, stable name used in diagnostics (e.g. `"clocks"`,
+    /// `"filesystem"`).
+    fn name(&self) -> &'static str;
+
+    /// Define this factor's `wasi:*` interfaces on `linker`.
+    fn configure_linker(&self, linker: &mut Linker<T>) -> Result<()>;
+}
+
+/// An ordered collection of [`WasiFactor`]s that together make up a host
+/// implementation.
+///
+/// Embedders build a registry out of only the factors they need: a factor
+/// left out of the registry defines nothing on the linker, and swapping in
+/// a custom clocks factor requires no changes to the rest of the context.
+/// [`ClocksFactor`] is the only factor this crate provides; filesystem,
+/// cli/environment, sockets and random have no factor here.
+pub struct FactorRegistry<T: 'static> {
+    factors: Vec<Box<dyn WasiFactor<T>>>,
+}
+
+impl<T: 'static> FactorRegistry<T> {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self { factors: Vec::new() }
+    }
+
+    /// Register `factor`, returning `self` for chaining.
+    pub fn with(mut self, factor: impl WasiFactor<T> + 'static) -> Self {
+        self.factors.push(Box::new(factor));
+        self
+    }
+
+    /// Register `factor` in place.
+    pub fn push(&mut self, factor: impl WasiFactor<T> + 'static) -> &mut Self {
+        self.factors.push(Box::new(factor));
+        self
+    }
+
+    /// The names of the registered factors, in registration order.
+    pub fn factor_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.factors.iter().map(|f| f.name())
+    }
+
+    /// Run `configure_linker` for every registered factor, in registration
+    /// order.
+    pub fn add_to_linker(&self, linker: &mut Linker<T>) -> Result<()> {
+        for factor in &self.factors {
+            factor
+                .configure_linker(linker)
+                .map_err(|e| e.context(format!("failed to configure `{}` factor", factor.name())))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: 'static> Default for FactorRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The per-instance state [`ClocksFactor`] needs: a wall clock and a
+/// monotonic clock, independent of the rest of [`WasiCtx`](super::WasiCtx).
+///
+/// An embedder who only wants to swap in a deterministic
+/// [`ManualClock`](super::ManualClock) builds one of these directly,
+/// instead of constructing a full `WasiCtx` just to reach its clocks.
+pub struct WasiClocks {
+    pub wall: Arc<dyn HostWallClock + Send + Sync>,
+    pub monotonic: Arc<dyn HostMonotonicClock + Send + Sync>,
+}
+
+impl wall_clock::Host for WasiClocks {
+    fn now(&mut self) -> Result<wall_clock::Datetime> {
+        let now = self.wall.now();
+        Ok(wall_clock::Datetime { seconds: now.as_secs(), nanoseconds: now.subsec_nanos() })
+    }
+
+    fn resolution(&mut self) -> Result<wall_clock::Datetime> {
+        let res = self.wall.resolution();
+        Ok(wall_clock::Datetime { seconds: res.as_secs(), nanoseconds: res.subsec_nanos() })
+    }
+}
+
+impl monotonic_clock::Host for WasiClocks {
+    fn now(&mut self) -> Result<u64> {
+        Ok(self.monotonic.now())
+    }
+
+    fn resolution(&mut self) -> Result<u64> {
+        Ok(self.monotonic.resolution())
+    }
+}
+
+/// A [`WasiFactor`] for `wasi:clocks`.
+///
+/// Unlike a full [`WasiView`](super::WasiView) implementation, `T` only
+/// needs to expose a [`WasiClocks`] projection -- `get` -- not a whole
+/// `WasiCtx`, so a store's data type can pull in clocks without also
+/// pulling in filesystem, stdio, or sockets.
+pub struct ClocksFactor<T> {
+    get: fn(&mut T) -> &mut WasiClocks,
+}
+
+impl<T: 'static> ClocksFactor<T> {
+    /// Create a clocks factor that reaches its per-instance state through
+    /// `get`.
+    pub fn new(get: fn(&mut T) -> &mut WasiClocks) -> Self {
+        Self { get }
+    }
+}
+
+impl<T: 'static> WasiFactor<T> for ClocksFactor<T> {
+    fn name(&self) -> &'static str {
+        "clocks"
+    }
+
+    fn configure_linker(&self, linker: &mut Linker<T>) -> Result<()> {
+        let get = self.get;
+        wall_clock::add_to_linker(linker, get)?;
+        monotonic_clock::add_to_linker(linker, get)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct CountingFactor {
+        name: &'static str,
+        calls: Arc<AtomicUsize>,
+    }
+
+    impl<T: 'static> WasiFactor<T> for CountingFactor {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn configure_linker(&self, _linker: &mut Linker<T>) -> Result<()> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn registry_runs_factors_in_order() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let registry: FactorRegistry<()> = FactorRegistry::new()
+            .with(CountingFactor { name: "clocks", calls: calls.clone() })
+            .with(CountingFactor { name: "filesystem", calls: calls.clone() });
+
+        assert_eq!(registry.factor_names().collect::<Vec<_>>(), ["clocks", "filesystem"]);
+
+        let engine = wasmtime::Engine::default();
+        let mut linker = Linker::new(&engine);
+        registry.add_to_linker(&mut linker).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn clocks_factor_wires_up_without_a_full_wasi_ctx() {
+        // `Linker<WasiClocks>`'s data type has no `WasiCtx`, no
+        // `ResourceTable`, no `WasiView` impl -- just the clocks state the
+        // factor asked for.
+        let (clock, controller) = super::super::ManualClock::new(Duration::from_secs(1));
+        controller.advance(Duration::from_nanos(42));
+        let mut clocks =
+            WasiClocks { wall: Arc::new(clock.clone()), monotonic: Arc::new(clock) };
+
+        let registry: FactorRegistry<WasiClocks> =
+            FactorRegistry::new().with(ClocksFactor::new(|clocks: &mut WasiClocks| clocks));
+
+        let engine = wasmtime::Engine::default();
+        let mut linker = Linker::new(&engine);
+        registry.add_to_linker(&mut linker).unwrap();
+
+        assert_eq!(monotonic_clock::Host::now(&mut clocks).unwrap(), 42);
+    }
+}
@@ -0,0 +1,195 @@
+//! A deterministic clock that can be driven forward on demand, waking any
+//! waker registered through [`ManualClock::subscribe`] whose deadline has
+//! passed.
+//!
+//! `FakeMonotonicClock`/`FakeWallClock`-style test doubles can only return
+//! canned values; there is no way to *advance* time and have pending
+//! subscriptions resolve. [`ManualClock`] fixes that: it tracks a shared
+//! `now`, and [`ManualClock::subscribe`] registers a waker's deadline in a
+//! min-heap. Calling [`ManualClockController::advance`] moves `now` forward
+//! and wakes every waker whose deadline is now in the past.
+//!
+//! [`ManualClock::subscribe`] is not `wasi:clocks`'s `subscribe-duration`/
+//! `subscribe-instant`, and this module doesn't implement or wire up
+//! `wasi:io/poll`'s `Host`/`Pollable` traits against it -- `api_time_manual`
+//! only proves the wake-up mechanism against a hand-rolled `poll_fn`, not an
+//! actual guest `pollable.block()` call. A component's own sleep/timeout
+//! calls do not resolve off this clock.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+use std::task::Waker;
+use std::time::Duration;
+
+use super::{HostMonotonicClock, HostWallClock};
+
+/// A pending [`ManualClock::subscribe`] registration: wake `waker` once
+/// monotonic time reaches `deadline_ns`.
+struct Timer {
+    deadline_ns: u64,
+    waker: Waker,
+}
+
+// `BinaryHeap` is a max-heap; wrap in `Reverse` so the soonest deadline
+// sorts first, and order purely by deadline (wakers aren't comparable).
+struct TimerEntry(Reverse<u64>, Timer);
+
+impl PartialEq for TimerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+impl Eq for TimerEntry {}
+impl PartialOrd for TimerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for TimerEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    monotonic_now: u64,
+    wall_now: Duration,
+    timers: BinaryHeap<TimerEntry>,
+}
+
+/// A [`HostMonotonicClock`] + [`HostWallClock`] pair backed by a shared,
+/// manually-advanced clock.
+///
+/// Clone this to hand a second handle to another part of the host
+/// implementation; all clones share the same underlying time and timer
+/// heap.
+#[derive(Clone)]
+pub struct ManualClock {
+    shared: Arc<Mutex<Shared>>,
+}
+
+/// A handle that advances a [`ManualClock`]'s notion of time and wakes any
+/// subscriptions whose deadline has passed.
+#[derive(Clone)]
+pub struct ManualClockController {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ManualClock {
+    /// Create a new manual clock starting at monotonic time `0` and wall
+    /// time `wall_start`, along with the [`ManualClockController`] used to
+    /// drive it.
+    pub fn new(wall_start: Duration) -> (Self, ManualClockController) {
+        let shared = Arc::new(Mutex::new(Shared {
+            monotonic_now: 0,
+            wall_now: wall_start,
+            timers: BinaryHeap::new(),
+        }));
+        (Self { shared: shared.clone() }, ManualClockController { shared })
+    }
+
+    /// Register a wake-up for `waker` once monotonic time reaches
+    /// `deadline_ns`. The `wasi:clocks` `subscribe-duration`/
+    /// `subscribe-instant` host functions do not call this.
+    ///
+    /// If `deadline_ns` has already passed, `waker` is woken immediately.
+    pub fn subscribe(&self, deadline_ns: u64, waker: Waker) {
+        let mut shared = self.shared.lock().unwrap();
+        if deadline_ns <= shared.monotonic_now {
+            drop(shared);
+            waker.wake();
+            return;
+        }
+        shared.timers.push(TimerEntry(Reverse(deadline_ns), Timer { deadline_ns, waker }));
+    }
+}
+
+impl HostWallClock for ManualClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(1)
+    }
+
+    fn now(&self) -> Duration {
+        self.shared.lock().unwrap().wall_now
+    }
+}
+
+impl HostMonotonicClock for ManualClock {
+    fn resolution(&self) -> u64 {
+        1
+    }
+
+    fn now(&self) -> u64 {
+        self.shared.lock().unwrap().monotonic_now
+    }
+}
+
+impl ManualClockController {
+    /// Move monotonic and wall time forward by `duration`, waking every
+    /// pending subscription whose deadline is now `<=` the new monotonic
+    /// time.
+    pub fn advance(&self, duration: Duration) {
+        let mut shared = self.shared.lock().unwrap();
+        shared.monotonic_now =
+            shared.monotonic_now.saturating_add(u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX));
+        shared.wall_now += duration;
+        let now = shared.monotonic_now;
+
+        let mut woken = Vec::new();
+        while let Some(entry) = shared.timers.peek() {
+            if entry.1.deadline_ns > now {
+                break;
+            }
+            woken.push(shared.timers.pop().unwrap());
+        }
+        // Wake after releasing the lock: a waker may re-enter and call
+        // `subscribe` again before this function returns.
+        drop(shared);
+        for TimerEntry(_, timer) in woken {
+            timer.waker.wake();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+
+    struct Flag(AtomicBool);
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn advance_wakes_expired_timers_only() {
+        let (clock, controller) = ManualClock::new(Duration::from_secs(0));
+
+        let early = Arc::new(Flag(AtomicBool::new(false)));
+        let late = Arc::new(Flag(AtomicBool::new(false)));
+        clock.subscribe(1_000, early.clone().into());
+        clock.subscribe(5_000, late.clone().into());
+
+        controller.advance(Duration::from_nanos(2_000));
+        assert!(early.0.load(Ordering::SeqCst));
+        assert!(!late.0.load(Ordering::SeqCst));
+
+        controller.advance(Duration::from_nanos(3_000));
+        assert!(late.0.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn subscribing_to_a_past_deadline_wakes_immediately() {
+        let (clock, controller) = ManualClock::new(Duration::from_secs(0));
+        controller.advance(Duration::from_nanos(10));
+
+        let flag = Arc::new(Flag(AtomicBool::new(false)));
+        clock.subscribe(5, flag.clone().into());
+        assert!(flag.0.load(Ordering::SeqCst));
+    }
+}
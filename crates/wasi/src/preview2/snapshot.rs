@@ -0,0 +1,159 @@
+//! Recipe replay for [`WasiCtx`], plus a standalone linear-memory
+//! checkpoint utility -- the two are independent tools, not (yet) one
+//! combined "checkpoint an instantiated reactor" feature.
+//!
+//! [`WasiCtxSnapshot`] does not checkpoint a live, instantiated reactor.
+//! `WasiCtx` holds boxed trait objects (stdio, RNG, sockets, ...) and isn't
+//! `Clone`, so there is nothing to actually snapshot once one has been
+//! built -- [`WasiCtxSnapshot::capture`] instead remembers the
+//! `WasiCtxBuilder` recipe the caller already wrote, and
+//! [`WasiCtxSnapshot::fork`] re-runs that recipe to hand back a fresh
+//! `WasiCtx` and an empty `ResourceTable`. Two forks of the same snapshot
+//! are guaranteed to start from equivalent host state; they do not share an
+//! instantiation, so this buys nothing over calling the same
+//! `WasiCtxBuilder` chain twice by hand, beyond not having to repeat it at
+//! each call site.
+//!
+//! [`StoreMemorySnapshot`] is the piece that could make an instantiated
+//! reactor's state reusable -- it captures and restores the raw bytes
+//! behind a `wasmtime::Memory` handle, independent of `WasiCtxSnapshot` or
+//! anything reactor-specific. It's unit-tested against a plain core
+//! `wasmtime::Instance`/`Memory`, where fetching the exported memory is a
+//! one-line `instance.get_memory(&mut store, "memory")`. Reaching a
+//! component instantiated through `bindgen!` (e.g. `TestReactor` in
+//! `tests/all/api.rs`) the same way -- finding and capturing *its*
+//! underlying linear memory from outside, then restoring it into a
+//! sibling instantiation before the two diverge -- is not something this
+//! change wires up or has proven out;
+//! `api_reactor_fork_replays_the_same_wasi_ctx_recipe` only exercises
+//! [`WasiCtxSnapshot`]'s recipe replay, and should be read as exactly that,
+//! not as a demonstration of resuming a warm reactor.
+
+use std::sync::Arc;
+
+use wasmtime::component::ResourceTable;
+use wasmtime::{AsContext, AsContextMut, Memory, StoreContextMut};
+
+use super::WasiCtx;
+
+/// A `WasiCtxBuilder` recipe, replayed on demand to produce equivalent but
+/// independent [`WasiCtx`]s -- not a checkpoint of any particular
+/// instantiated `WasiCtx`. See the module docs for what this does and does
+/// not buy over calling the builder chain directly.
+///
+/// [`fork`](WasiCtxSnapshot::fork) always starts from a fresh, empty
+/// `ResourceTable`, so resources obtained through one fork (streams,
+/// descriptors) are never visible to another.
+pub struct WasiCtxSnapshot {
+    rebuild: Arc<dyn Fn() -> WasiCtx + Send + Sync>,
+}
+
+impl WasiCtxSnapshot {
+    /// Capture a checkpoint from `rebuild`, a closure that deterministically
+    /// reconstructs the same [`WasiCtx`] each time it's called (e.g.
+    /// `|| WasiCtxBuilder::new().env("GOOD_DOG", "gussie").build()`).
+    ///
+    /// `rebuild` must not depend on anything that changes between calls --
+    /// it is the single source of truth for what "the checkpoint" is, since
+    /// there is no already-built `WasiCtx` to clone from.
+    pub fn capture(rebuild: impl Fn() -> WasiCtx + Send + Sync + 'static) -> Self {
+        Self { rebuild: Arc::new(rebuild) }
+    }
+
+    /// Produce a fresh [`WasiCtx`] and [`ResourceTable`] from this
+    /// checkpoint, suitable for driving a new, independent sequence of
+    /// export calls.
+    ///
+    /// The returned `ResourceTable` is empty: any resources obtained by
+    /// earlier calls on another fork are not visible here and must be
+    /// re-derived (e.g. by re-opening a preopen).
+    pub fn fork(&self) -> (WasiCtx, ResourceTable) {
+        ((self.rebuild)(), ResourceTable::new())
+    }
+}
+
+/// A snapshot of a `Store`'s exported linear memory, taken so a later
+/// [`restore`](StoreMemorySnapshot::restore) can rewind a *different*
+/// store (instantiated from the same component) back to this point.
+///
+/// This only captures the bytes backing `memory`; it does not capture
+/// table state, globals, or host resources -- pair it with
+/// [`WasiCtxSnapshot`] to additionally reset host-side state.
+pub struct StoreMemorySnapshot {
+    bytes: Vec<u8>,
+}
+
+impl StoreMemorySnapshot {
+    /// Copy out the current contents of `memory` as exported from `store`.
+    pub fn capture<T>(store: impl AsContext<Data = T>, memory: Memory) -> Self {
+        Self { bytes: memory.data(store.as_context()).to_vec() }
+    }
+
+    /// Overwrite `memory` in `store` with the bytes captured by
+    /// [`capture`](Self::capture).
+    ///
+    /// This never shrinks or grows the destination memory. Returns an
+    /// error instead of panicking if `memory` is smaller than the
+    /// snapshot -- a forked store instantiated from a different build of
+    /// the component, or one that grew its memory differently before the
+    /// snapshot was taken, is not guaranteed to match in size.
+    pub fn restore<T>(&self, mut store: impl AsContextMut<Data = T>, memory: Memory) -> anyhow::Result<()> {
+        let mut ctx: StoreContextMut<'_, T> = store.as_context_mut();
+        let dst = memory.data_mut(&mut ctx);
+        anyhow::ensure!(
+            dst.len() >= self.bytes.len(),
+            "destination memory ({} bytes) is smaller than the snapshot ({} bytes)",
+            dst.len(),
+            self.bytes.len(),
+        );
+        dst[..self.bytes.len()].copy_from_slice(&self.bytes);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasmtime::{Engine, Instance, Module, Store};
+
+    fn memory_module(min_pages: u32) -> Module {
+        let engine = Engine::default();
+        Module::new(&engine, format!("(module (memory (export \"memory\") {min_pages}))")).unwrap()
+    }
+
+    #[test]
+    fn restore_errors_instead_of_panicking_on_undersized_memory() {
+        let engine = Engine::default();
+
+        let mut store_a = Store::new(&engine, ());
+        let big = Instance::new(&mut store_a, &memory_module(2), &[]).unwrap();
+        let memory_a = big.get_memory(&mut store_a, "memory").unwrap();
+        memory_a.data_mut(&mut store_a)[0] = 0xAB;
+        let snapshot = StoreMemorySnapshot::capture(&store_a, memory_a);
+
+        let mut store_b = Store::new(&engine, ());
+        let small = Instance::new(&mut store_b, &memory_module(1), &[]).unwrap();
+        let memory_b = small.get_memory(&mut store_b, "memory").unwrap();
+
+        let err = snapshot.restore(&mut store_b, memory_b).unwrap_err();
+        assert!(err.to_string().contains("smaller than the snapshot"), "{err}");
+    }
+
+    #[test]
+    fn restore_round_trips_captured_bytes() {
+        let engine = Engine::default();
+
+        let mut store_a = Store::new(&engine, ());
+        let instance_a = Instance::new(&mut store_a, &memory_module(1), &[]).unwrap();
+        let memory_a = instance_a.get_memory(&mut store_a, "memory").unwrap();
+        memory_a.data_mut(&mut store_a)[..3].copy_from_slice(b"hi!");
+        let snapshot = StoreMemorySnapshot::capture(&store_a, memory_a);
+
+        let mut store_b = Store::new(&engine, ());
+        let instance_b = Instance::new(&mut store_b, &memory_module(1), &[]).unwrap();
+        let memory_b = instance_b.get_memory(&mut store_b, "memory").unwrap();
+
+        snapshot.restore(&mut store_b, memory_b).unwrap();
+        assert_eq!(&memory_b.data(&store_b)[..3], b"hi!");
+    }
+}
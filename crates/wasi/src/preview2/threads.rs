@@ -0,0 +1,197 @@
+//! A host-side helper for spawning reactor workers into their own `Store`,
+//! as a building block towards a guest-callable thread-spawn interface.
+//!
+//! The `preview2` command/reactor path normally instantiates a single
+//! `Store` and drives it from one task. [`WasiThreadsCtx::spawn`] takes a
+//! `WasiCtx` and an `with_instance` callback and runs that callback against
+//! a freshly-created `Store` (with its own, empty [`ResourceTable`]) on a
+//! dedicated `tokio` task; the callback does the instantiation itself.
+//!
+//! Nothing here defines a `wasi:threads` host import, so a component
+//! running inside one of these stores has no way to ask for a worker
+//! itself -- only the embedder can call [`WasiThreadsCtx::spawn`]. Whether
+//! `wasi:threads` should even be modeled as a factor-style interface or
+//! something that needs deeper `Store`/`Linker` plumbing is still an open
+//! question, so this module sticks to the one piece that's unambiguous
+//! regardless of how that turns out: running a callback against its own
+//! `Store` on a dedicated task, with an explicit say over what that
+//! `Store`'s `ResourceTable` starts with. That's why `spawn` takes a
+//! `share` callback instead of just cloning the parent's table wholesale
+//! -- a new worker otherwise has no resources in scope at all, and
+//! [`share_resource`] is the only way to put one there, by copying a
+//! `Clone` value's current state into the child table rather than aliasing
+//! the parent's entry.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use wasmtime::component::{Component, Linker, Resource, ResourceTable};
+use wasmtime::Store;
+
+use super::WasiCtx;
+
+/// Copy the value behind `resource` from `from` into `to`, returning the
+/// new table's handle for it.
+///
+/// This is the one sanctioned way to give a spawned worker's table
+/// visibility into a specific resource from the caller's table: `to` must
+/// otherwise be assumed empty (see [`WasiThreadsCtx::spawn`]'s `share`
+/// parameter), and there is no way to move or alias a single table entry
+/// across two `ResourceTable`s, only to copy a `Clone` value into a new
+/// slot. Sharing mutable state this way therefore requires `T` itself to
+/// be cheaply, meaningfully cloneable (e.g. `Arc<Mutex<_>>`-backed), the
+/// same assumption the rest of this crate makes about shared output
+/// streams.
+pub fn share_resource<T: Clone + Send + Sync + 'static>(
+    from: &ResourceTable,
+    resource: &Resource<T>,
+    to: &mut ResourceTable,
+) -> Result<Resource<T>> {
+    let value = from.get(resource).context("resource not present in the source table")?;
+    to.push(value.clone()).context("failed to push shared resource into the destination table")
+}
+
+/// An identifier for a spawned worker, unique within a [`WasiThreadsCtx`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ThreadId(u32);
+
+/// Host state for spawning additional workers, each in their own `Store`
+/// on a separate `tokio` task.
+///
+/// `T` is the store data type of the component being spawned; it must be
+/// constructible from a `WasiCtx` and a fresh `ResourceTable` via
+/// `new_store_data`.
+pub struct WasiThreadsCtx<T> {
+    engine: wasmtime::Engine,
+    linker: Arc<Linker<T>>,
+    component: Component,
+    next_id: AtomicU32,
+    new_store_data: Arc<dyn Fn(WasiCtx, ResourceTable) -> T + Send + Sync>,
+}
+
+impl<T: Send + 'static> WasiThreadsCtx<T> {
+    /// Create a new thread-spawning context for `component`, instantiated
+    /// through `linker` on `engine`. `new_store_data` builds a fresh
+    /// store-data value for each spawned worker out of a cloned `WasiCtx`
+    /// and an empty `ResourceTable`.
+    pub fn new(
+        engine: wasmtime::Engine,
+        linker: Linker<T>,
+        component: Component,
+        new_store_data: impl Fn(WasiCtx, ResourceTable) -> T + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            engine,
+            linker: Arc::new(linker),
+            component,
+            next_id: AtomicU32::new(0),
+            new_store_data: Arc::new(new_store_data),
+        }
+    }
+
+    /// Spawn a new worker that takes ownership of `wasi` and runs
+    /// `with_instance` against its own freshly-created `Store` on a
+    /// dedicated `tokio` task.
+    ///
+    /// `share` runs first, against the worker's new, empty `ResourceTable`
+    /// -- use [`share_resource`] in its body for each resource (e.g. a
+    /// shared output stream) the worker should see; anything not pushed
+    /// there is invisible to it. If `share` errors, the worker is never
+    /// instantiated and the error is delivered through the returned
+    /// `JoinHandle` instead.
+    ///
+    /// `with_instance` is then handed the new store and should instantiate
+    /// and call whatever export the worker is meant to run; any value it
+    /// returns is delivered through the returned `JoinHandle`.
+    pub fn spawn<S, F, Fut, R>(
+        &self,
+        wasi: WasiCtx,
+        share: S,
+        with_instance: F,
+    ) -> (ThreadId, tokio::task::JoinHandle<Result<R>>)
+    where
+        S: FnOnce(&mut ResourceTable) -> Result<()>,
+        F: FnOnce(Store<T>, Arc<Linker<T>>, Component) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<R>> + Send,
+        R: Send + 'static,
+    {
+        let id = ThreadId(self.next_id.fetch_add(1, Ordering::SeqCst));
+
+        let mut table = ResourceTable::new();
+        let store = share(&mut table).map(|()| Store::new(&self.engine, (self.new_store_data)(wasi, table)));
+        let linker = self.linker.clone();
+        let component = self.component.clone();
+
+        let handle = tokio::task::spawn(async move {
+            with_instance(store?, linker, component).await
+        });
+        (id, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn thread_ids_are_distinct_and_increasing() {
+        // Exercises the id-allocation behavior without needing a real
+        // component: `spawn` is generic over what `with_instance` does.
+        let engine = wasmtime::Engine::default();
+        let linker = Linker::<(WasiCtx, ResourceTable)>::new(&engine);
+        let wat = "(component)";
+        let component = Component::new(&engine, wat).unwrap();
+
+        let ctx = WasiThreadsCtx::new(engine, linker, component, |wasi, table| (wasi, table));
+
+        let (id_a, handle_a) = ctx.spawn(
+            super::super::WasiCtxBuilder::new().build(),
+            |_table| Ok(()),
+            |_store, _linker, _component| async { Ok(()) },
+        );
+        let (id_b, handle_b) = ctx.spawn(
+            super::super::WasiCtxBuilder::new().build(),
+            |_table| Ok(()),
+            |_store, _linker, _component| async { Ok(()) },
+        );
+
+        assert_ne!(id_a, id_b);
+        assert!(id_a.0 < id_b.0);
+        handle_a.await.unwrap().unwrap();
+        handle_b.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn share_puts_only_the_named_resource_in_the_child_table() {
+        let mut parent = ResourceTable::new();
+        let shared = parent.push(Arc::new(std::sync::Mutex::new(7_i32))).unwrap();
+        let unshared = parent.push("not shared".to_string()).unwrap();
+
+        let mut child = ResourceTable::new();
+        let shared_in_child = share_resource(&parent, &shared, &mut child).unwrap();
+
+        assert_eq!(*child.get(&shared_in_child).unwrap().lock().unwrap(), 7);
+        // `unshared` was never pushed into `child`, so looking it up there
+        // by the same index must fail.
+        assert!(child.get(&Resource::<String>::new_own(unshared.rep())).is_err());
+    }
+
+    #[tokio::test]
+    async fn spawn_surfaces_a_share_error_without_instantiating() {
+        let engine = wasmtime::Engine::default();
+        let linker = Linker::<(WasiCtx, ResourceTable)>::new(&engine);
+        let component = Component::new(&engine, "(component)").unwrap();
+        let ctx = WasiThreadsCtx::new(engine, linker, component, |wasi, table| (wasi, table));
+
+        let (_id, handle) = ctx.spawn::<_, _, _, ()>(
+            super::super::WasiCtxBuilder::new().build(),
+            |_table| anyhow::bail!("deliberately refuse to share anything"),
+            |_store, _linker, _component| async { unreachable!("must not instantiate") },
+        );
+
+        let err = handle.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("deliberately refuse"));
+    }
+}
@@ -0,0 +1,249 @@
+//! An in-memory directory/file/symlink tree, plus the `wasi:filesystem/
+//! types` metadata ([`VirtualDir::stat`]) for each entry.
+//!
+//! Be clear about what this is *not*: it is not yet a
+//! `WasiCtxBuilder::preopened_dir_virtual` entry point, and nothing in
+//! `tests/all/api.rs` is hermetic because of it -- `api_read_only` is
+//! unchanged and still writes `bar.txt` to a real temp directory through
+//! `cap_std::fs::Dir`. Getting from this tree to an actual preopen means
+//! implementing `wasi:filesystem/types::HostDescriptor` -- read/write-via-
+//! stream, `read-directory`, `advise`, `link-at`, `open-at`, and roughly
+//! fifteen more methods -- over a [`VirtualDir`], none of which this
+//! snapshot has a reference copy of to check an implementation against. I'd
+//! rather this module stay an honest, narrowly-scoped tree-plus-metadata
+//! utility than a `Descriptor` impl that type-checks but quietly gets a
+//! rarely-exercised method wrong. Wiring it up as a real preopen is future
+//! work, not something this change claims to have done.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use crate::preview2::bindings::wasi::filesystem::types as filesystem;
+
+/// A single entry in a [`VirtualDirBuilder`] tree.
+#[derive(Clone, Debug)]
+pub enum VirtualDirEntry {
+    /// A regular file with the given byte contents.
+    File(Vec<u8>),
+    /// A subdirectory.
+    Dir(VirtualDirBuilder),
+    /// A symlink to the given (relative) target path.
+    Symlink(String),
+}
+
+/// A builder for an in-memory directory tree, keyed by entry name.
+///
+/// Entries are stored in a [`BTreeMap`] so directory iteration order (as
+/// returned by [`VirtualDir::read_dir`]) is stable and deterministic.
+#[derive(Clone, Debug, Default)]
+pub struct VirtualDirBuilder {
+    entries: BTreeMap<String, VirtualDirEntry>,
+}
+
+impl VirtualDirBuilder {
+    /// Create an empty directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file with the given contents, returning `self` for chaining.
+    pub fn file(mut self, name: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        self.entries.insert(name.into(), VirtualDirEntry::File(contents.into()));
+        self
+    }
+
+    /// Add a subdirectory, returning `self` for chaining.
+    pub fn dir(mut self, name: impl Into<String>, dir: VirtualDirBuilder) -> Self {
+        self.entries.insert(name.into(), VirtualDirEntry::Dir(dir));
+        self
+    }
+
+    /// Add a symlink pointing at `target`, returning `self` for chaining.
+    pub fn symlink(mut self, name: impl Into<String>, target: impl Into<String>) -> Self {
+        self.entries.insert(name.into(), VirtualDirEntry::Symlink(target.into()));
+        self
+    }
+
+    fn build(self) -> VirtualDirNode {
+        VirtualDirNode {
+            entries: self
+                .entries
+                .into_iter()
+                .map(|(name, entry)| {
+                    let node = match entry {
+                        VirtualDirEntry::File(contents) => VirtualNode::File(RwLock::new(contents)),
+                        VirtualDirEntry::Dir(dir) => VirtualNode::Dir(dir.build()),
+                        VirtualDirEntry::Symlink(target) => VirtualNode::Symlink(target),
+                    };
+                    (name, node)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum VirtualNode {
+    File(RwLock<Vec<u8>>),
+    Dir(VirtualDirNode),
+    Symlink(String),
+}
+
+#[derive(Debug, Default)]
+struct VirtualDirNode {
+    entries: BTreeMap<String, VirtualNode>,
+}
+
+/// A handle to an in-memory directory tree.
+///
+/// This only ever touches process memory and never resolves a path against
+/// the host filesystem. It is not a `wasi:filesystem` descriptor and cannot
+/// be passed to `WasiCtxBuilder` as a preopen; it is read and written
+/// through its own methods only.
+#[derive(Clone, Debug)]
+pub struct VirtualDir {
+    root: Arc<VirtualDirNode>,
+}
+
+impl VirtualDir {
+    /// Finalize a [`VirtualDirBuilder`] tree into a [`VirtualDir`].
+    pub fn from_builder(root: VirtualDirBuilder) -> Self {
+        Self { root: Arc::new(root.build()) }
+    }
+
+    fn lookup(&self, path: &str) -> Option<&VirtualNode> {
+        let mut dir = &*self.root;
+        let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+        while let Some(component) = components.next() {
+            let node = dir.entries.get(component)?;
+            if components.peek().is_none() {
+                return Some(node);
+            }
+            match node {
+                VirtualNode::Dir(next) => dir = next,
+                // A non-final path component that isn't a directory can't
+                // be descended into.
+                _ => return None,
+            }
+        }
+        None
+    }
+
+    /// The `wasi:filesystem/types.stat` metadata for the entry at `path`,
+    /// or `None` if there is nothing there.
+    pub fn stat(&self, path: &str) -> Option<filesystem::DescriptorStat> {
+        let (type_, size) = match self.lookup(path)? {
+            VirtualNode::File(contents) => {
+                (filesystem::DescriptorType::RegularFile, contents.read().unwrap().len() as u64)
+            }
+            VirtualNode::Dir(_) => (filesystem::DescriptorType::Directory, 0),
+            VirtualNode::Symlink(_) => (filesystem::DescriptorType::SymbolicLink, 0),
+        };
+        Some(filesystem::DescriptorStat {
+            type_,
+            link_count: 1,
+            size,
+            data_access_timestamp: None,
+            data_modification_timestamp: None,
+            status_change_timestamp: None,
+        })
+    }
+
+    /// Read the full contents of the file at `path`, or `None` if there is
+    /// no file there.
+    pub fn read_file(&self, path: &str) -> Option<Vec<u8>> {
+        match self.lookup(path)? {
+            VirtualNode::File(contents) => Some(contents.read().unwrap().clone()),
+            _ => None,
+        }
+    }
+
+    /// Overwrite the contents of the file at `path`. Returns `false` if
+    /// there is no file there.
+    pub fn write_file(&self, path: &str, contents: Vec<u8>) -> bool {
+        match self.lookup(path) {
+            Some(VirtualNode::File(existing)) => {
+                *existing.write().unwrap() = contents;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The sorted names of the entries directly inside the directory at
+    /// `path`, or `None` if `path` is not a directory.
+    pub fn read_dir(&self, path: &str) -> Option<Vec<String>> {
+        let node = if path.is_empty() || path == "/" {
+            &*self.root
+        } else {
+            match self.lookup(path)? {
+                VirtualNode::Dir(dir) => dir,
+                _ => return None,
+            }
+        };
+        Some(node.entries.keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_nested_file_contents() {
+        let tree = VirtualDirBuilder::new()
+            .file("bar.txt", b"And stood awhile in thought".to_vec())
+            .dir("sub", VirtualDirBuilder::new().file("nested.txt", b"hi".to_vec()));
+        let dir = VirtualDir::from_builder(tree);
+
+        assert_eq!(dir.read_file("bar.txt").unwrap(), b"And stood awhile in thought");
+        assert_eq!(dir.read_file("sub/nested.txt").unwrap(), b"hi");
+        assert_eq!(dir.read_dir("/").unwrap(), vec!["bar.txt".to_string(), "sub".to_string()]);
+    }
+
+    #[test]
+    fn lookup_descends_more_than_one_directory_level() {
+        // Regression test: `lookup` used to look a path component back up
+        // inside the *child* directory it had just descended into (which
+        // never has that name), panicking on any path nested more than one
+        // level deep.
+        let tree = VirtualDirBuilder::new().dir(
+            "a",
+            VirtualDirBuilder::new().dir("b", VirtualDirBuilder::new().file("c.txt", b"deep".to_vec())),
+        );
+        let dir = VirtualDir::from_builder(tree);
+
+        assert_eq!(dir.read_file("a/b/c.txt").unwrap(), b"deep");
+        assert_eq!(dir.read_dir("a/b").unwrap(), vec!["c.txt".to_string()]);
+        assert!(dir.read_file("a/b/missing.txt").is_none());
+    }
+
+    #[test]
+    fn writes_are_visible_to_later_reads() {
+        let dir = VirtualDir::from_builder(VirtualDirBuilder::new().file("a", b"old".to_vec()));
+        assert!(dir.write_file("a", b"new".to_vec()));
+        assert_eq!(dir.read_file("a").unwrap(), b"new");
+        assert!(!dir.write_file("missing", b"x".to_vec()));
+    }
+
+    #[test]
+    fn stat_reports_type_and_size_for_nested_entries() {
+        let tree = VirtualDirBuilder::new()
+            .file("bar.txt", b"And stood awhile in thought".to_vec())
+            .dir("sub", VirtualDirBuilder::new().file("nested.txt", b"hi".to_vec()));
+        let dir = VirtualDir::from_builder(tree);
+
+        let file_stat = dir.stat("bar.txt").unwrap();
+        assert_eq!(file_stat.type_, filesystem::DescriptorType::RegularFile);
+        assert_eq!(file_stat.size, 27);
+
+        let nested_stat = dir.stat("sub/nested.txt").unwrap();
+        assert_eq!(nested_stat.type_, filesystem::DescriptorType::RegularFile);
+        assert_eq!(nested_stat.size, 2);
+
+        let dir_stat = dir.stat("sub").unwrap();
+        assert_eq!(dir_stat.type_, filesystem::DescriptorType::Directory);
+
+        assert!(dir.stat("sub/missing.txt").is_none());
+    }
+}
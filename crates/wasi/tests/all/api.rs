@@ -2,7 +2,7 @@ use anyhow::Result;
 use cap_std::ambient_authority;
 use cap_std::fs::Dir;
 use std::io::Write;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use wasmtime::component::{Component, Linker, ResourceTable};
 use wasmtime::{Config, Engine, Store};
@@ -10,12 +10,25 @@ use wasmtime_wasi::preview2::bindings::wasi::clocks::wall_clock;
 use wasmtime_wasi::preview2::bindings::wasi::filesystem::types as filesystem;
 use wasmtime_wasi::preview2::command::{add_to_linker, Command};
 use wasmtime_wasi::preview2::{
-    self, DirPerms, FilePerms, HostMonotonicClock, HostWallClock, WasiCtx, WasiCtxBuilder, WasiView,
+    self, DirPerms, FilePerms, HostMonotonicClock, HostWallClock, ManualClock, VirtualDirBuilder,
+    WasiCtx, WasiCtxBuilder, WasiView,
 };
+use wasmtime_wasi::preview2::profiling::{ComponentCallProfiler, HasComponentCallProfiler, NoopProfiler};
 
 struct CommandCtx {
     table: ResourceTable,
     wasi: WasiCtx,
+    profiler: Arc<dyn ComponentCallProfiler>,
+}
+
+impl CommandCtx {
+    fn new(table: ResourceTable, wasi: WasiCtx) -> Self {
+        Self { table, wasi, profiler: Arc::new(NoopProfiler) }
+    }
+
+    fn with_profiler(table: ResourceTable, wasi: WasiCtx, profiler: Arc<dyn ComponentCallProfiler>) -> Self {
+        Self { table, wasi, profiler }
+    }
 }
 
 impl WasiView for CommandCtx {
@@ -33,6 +46,12 @@ impl WasiView for CommandCtx {
     }
 }
 
+impl HasComponentCallProfiler for CommandCtx {
+    fn component_call_profiler(&self) -> &Arc<dyn ComponentCallProfiler> {
+        &self.profiler
+    }
+}
+
 use test_programs_artifacts::*;
 
 foreach_api!(assert_test_exists);
@@ -87,7 +106,7 @@ async fn api_time() -> Result<()> {
         .wall_clock(FakeWallClock)
         .build();
 
-    let (mut store, command) = instantiate(API_TIME_COMPONENT, CommandCtx { table, wasi }).await?;
+    let (mut store, command) = instantiate(API_TIME_COMPONENT, CommandCtx::new(table, wasi)).await?;
 
     command
         .wasi_cli_run()
@@ -96,6 +115,41 @@ async fn api_time() -> Result<()> {
         .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
 }
 
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn api_time_manual() -> Result<()> {
+    use std::future::poll_fn;
+    use std::task::Poll;
+
+    let (clock, controller) = ManualClock::new(Duration::from_secs(0));
+
+    // This proves the wake-up mechanism with a hand-rolled `poll_fn`, not
+    // an actual guest sleep -- `ManualClock` isn't wired into a real
+    // `wasi:io/poll` implementation (see `manual_clock`'s module docs). The
+    // future only completes once `controller.advance` pushes monotonic
+    // time past the deadline, with no real wall-clock delay involved.
+    let deadline = HostMonotonicClock::now(&clock) + 10;
+    let mut subscribed = false;
+    let wait = poll_fn(move |cx| {
+        if HostMonotonicClock::now(&clock) >= deadline {
+            return Poll::Ready(());
+        }
+        if !subscribed {
+            subscribed = true;
+            clock.subscribe(deadline, cx.waker().clone());
+        }
+        Poll::Pending
+    });
+    let waiter = tokio::spawn(wait);
+
+    // Give the spawned task a chance to register its subscription before
+    // we advance time past the deadline.
+    tokio::task::yield_now().await;
+    controller.advance(Duration::from_nanos(10));
+
+    waiter.await?;
+    Ok(())
+}
+
 #[test_log::test(tokio::test(flavor = "multi_thread"))]
 async fn api_read_only() -> Result<()> {
     let dir = tempfile::tempdir()?;
@@ -110,7 +164,7 @@ async fn api_read_only() -> Result<()> {
         .build();
 
     let (mut store, command) =
-        instantiate(API_READ_ONLY_COMPONENT, CommandCtx { table, wasi }).await?;
+        instantiate(API_READ_ONLY_COMPONENT, CommandCtx::new(table, wasi)).await?;
 
     command
         .wasi_cli_run()
@@ -119,6 +173,24 @@ async fn api_read_only() -> Result<()> {
         .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
 }
 
+// Note: this does *not* make `api_read_only` above hermetic, and isn't
+// meant to read as if it did -- it only covers `VirtualDir`'s own tree and
+// `stat` behavior in isolation. `api_read_only` still preopens a real
+// `cap_std::fs::Dir` over a real temp directory; turning that into an
+// in-memory preopen needs `wasi:filesystem/types::HostDescriptor`
+// implemented over a `VirtualDir` (see `virtual_fs`'s module docs), which
+// this series doesn't attempt.
+#[test]
+fn virtual_dir_stat_and_read_match_a_hand_built_tree() {
+    let tree = VirtualDirBuilder::new()
+        .file("bar.txt", b"And stood awhile in thought".to_vec())
+        .dir("sub", VirtualDirBuilder::new());
+    let dir = preview2::virtual_fs::VirtualDir::from_builder(tree);
+
+    assert_eq!(dir.read_file("bar.txt").unwrap(), b"And stood awhile in thought");
+    assert_eq!(dir.stat("sub").unwrap().type_, filesystem::DescriptorType::Directory);
+}
+
 // This is tested in the wasi-http crate, but need to satisfy the `foreach_api!`
 // macro above.
 #[allow(dead_code)]
@@ -163,7 +235,7 @@ async fn api_reactor() -> Result<()> {
     let mut linker = Linker::new(&engine);
     add_to_linker(&mut linker)?;
 
-    let mut store = Store::new(&engine, CommandCtx { table, wasi });
+    let mut store = Store::new(&engine, CommandCtx::new(table, wasi));
     let component = Component::from_file(&engine, API_REACTOR_COMPONENT)?;
     let (reactor, _instance) =
         TestReactor::instantiate_async(&mut store, &component, &linker).await?;
@@ -215,3 +287,144 @@ async fn api_reactor() -> Result<()> {
 
     Ok(())
 }
+
+#[test_log::test(tokio::test)]
+async fn api_reactor_fork_replays_the_same_wasi_ctx_recipe() -> Result<()> {
+    // This does *not* checkpoint an instantiated reactor -- both stores
+    // below pay the full instantiation cost and share nothing but the
+    // builder recipe (see `snapshot`'s module docs for why `WasiCtxSnapshot`
+    // can't do more than that, and what `StoreMemorySnapshot` would still
+    // need in order to). What this shows: forking the same recipe twice
+    // gives two reactor instances with identical starting host state, and
+    // mutating one (adding strings) doesn't leak into the other.
+    let snapshot =
+        preview2::WasiCtxSnapshot::capture(|| WasiCtxBuilder::new().env("GOOD_DOG", "gussie").build());
+
+    let mut config = Config::new();
+    config.async_support(true).wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let mut linker = Linker::new(&engine);
+    add_to_linker(&mut linker)?;
+    let component = Component::from_file(&engine, API_REACTOR_COMPONENT)?;
+
+    async fn instantiate_reactor(
+        engine: &Engine,
+        linker: &Linker<CommandCtx>,
+        component: &Component,
+        snapshot: &preview2::WasiCtxSnapshot,
+    ) -> Result<(Store<CommandCtx>, TestReactor)> {
+        let (wasi, table) = snapshot.fork();
+        let mut store = Store::new(engine, CommandCtx::new(table, wasi));
+        let (reactor, _instance) = TestReactor::instantiate_async(&mut store, component, linker).await?;
+        Ok((store, reactor))
+    }
+
+    let (mut store_a, reactor_a) = instantiate_reactor(&engine, &linker, &component, &snapshot).await?;
+    let (mut store_b, reactor_b) = instantiate_reactor(&engine, &linker, &component, &snapshot).await?;
+
+    reactor_a.call_add_strings(&mut store_a, &["hello", "$GOOD_DOG"]).await?;
+    reactor_b.call_add_strings(&mut store_b, &["just", "$GOOD_DOG"]).await?;
+
+    assert_eq!(reactor_a.call_get_strings(&mut store_a).await?, &["hello", "gussie"]);
+    assert_eq!(reactor_b.call_get_strings(&mut store_b).await?, &["just", "gussie"]);
+
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn api_reactor_worker_thread() -> Result<()> {
+    // A worker spawned via `WasiThreadsCtx` gets its own `Store` and
+    // `ResourceTable` and drives its own `call_add_strings` independently
+    // of the parent -- it's configured with an equivalent `WasiCtx` here,
+    // but that's this test's choice, not something `spawn` enforces. It
+    // shares nothing from a parent table, since it doesn't have one.
+    let wasi = WasiCtxBuilder::new().env("GOOD_DOG", "gussie").build();
+
+    let mut config = Config::new();
+    config.async_support(true).wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let mut linker = Linker::new(&engine);
+    add_to_linker(&mut linker)?;
+    let component = Component::from_file(&engine, API_REACTOR_COMPONENT)?;
+
+    let threads = preview2::WasiThreadsCtx::new(engine.clone(), linker, component.clone(), |wasi, table| {
+        CommandCtx::new(table, wasi)
+    });
+
+    let (_id, worker) = threads.spawn(
+        wasi,
+        |_table| Ok(()),
+        move |mut store, linker, component| async move {
+            let (reactor, _instance) = TestReactor::instantiate_async(&mut store, &component, &linker).await?;
+            let n = reactor.call_add_strings(&mut store, &["from", "the", "worker"]).await?;
+            Ok(n)
+        },
+    );
+
+    assert_eq!(worker.await??, 3);
+    Ok(())
+}
+
+#[test_log::test(tokio::test)]
+async fn api_reactor_profiling() -> Result<()> {
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingProfiler(Mutex<Vec<(&'static str, String)>>);
+
+    impl ComponentCallProfiler for RecordingProfiler {
+        fn begin(&self, _component: &str, export: &str) {
+            self.0.lock().unwrap().push(("begin", export.to_string()));
+        }
+        fn end(&self, _component: &str, export: &str) {
+            self.0.lock().unwrap().push(("end", export.to_string()));
+        }
+    }
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new().env("GOOD_DOG", "gussie").build();
+    let profiler = Arc::new(RecordingProfiler::default());
+
+    let mut config = Config::new();
+    config.async_support(true).wasm_component_model(true);
+    let engine = Engine::new(&config)?;
+    let mut linker = Linker::new(&engine);
+    add_to_linker(&mut linker)?;
+
+    // The profiling agent lives on the store's data (via
+    // `HasComponentCallProfiler`), so it's read from there once rather
+    // than threaded through separately; it's cloned out once up front
+    // since the calls themselves need `&mut store`.
+    let mut store = Store::new(&engine, CommandCtx::with_profiler(table, wasi, profiler.clone()));
+    let component = Component::from_file(&engine, API_REACTOR_COMPONENT)?;
+
+    let agent = store.data().component_call_profiler().clone();
+    let (reactor, _instance) = wasmtime_wasi::profiled!(
+        &agent,
+        "test-reactor",
+        "<instantiate>",
+        TestReactor::instantiate_async(&mut store, &component, &linker).await
+    )
+    .await?;
+
+    let n = wasmtime_wasi::profiled!(
+        &agent,
+        "test-reactor",
+        "call_add_strings",
+        reactor.call_add_strings(&mut store, &["hello", "$GOOD_DOG"]).await
+    )
+    .await?;
+    assert_eq!(n, 2);
+
+    assert_eq!(
+        *profiler.0.lock().unwrap(),
+        vec![
+            ("begin", "<instantiate>".to_string()),
+            ("end", "<instantiate>".to_string()),
+            ("begin", "call_add_strings".to_string()),
+            ("end", "call_add_strings".to_string()),
+        ]
+    );
+
+    Ok(())
+}